@@ -8,70 +8,142 @@ use syn::{
 };
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum CollectionKind {
+    Vec,
+    VecDeque,
+    HashSet,
+    BTreeSet,
+    BinaryHeap,
+}
+
+impl CollectionKind {
+    const ALL: &'static [(&'static str, Self)] = &[
+        ("Vec", Self::Vec),
+        ("VecDeque", Self::VecDeque),
+        ("HashSet", Self::HashSet),
+        ("BTreeSet", Self::BTreeSet),
+        ("BinaryHeap", Self::BinaryHeap),
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Vec => "Vec",
+            Self::VecDeque => "VecDeque",
+            Self::HashSet => "HashSet",
+            Self::BTreeSet => "BTreeSet",
+            Self::BinaryHeap => "BinaryHeap",
+        }
+    }
+}
+
+/// Recursive shape of a field's type, mirroring cynic-codegen's `RustType`.
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub enum Ty {
+    /// `()`
     Unit,
-    Vec,
-    VecVec,
-    Option,
-    OptionOption,
-    OptionVec,
-    OptionVecVec,
-    Other,
+    Leaf,
+    /// `Box<_>`/`Rc<_>`/`Arc<_>`, naming the wrapper so codegen can re-apply it.
+    Pointer(&'static str, Box<Sp<Ty>>),
+    /// `Option<_>`
+    Optional(Box<Sp<Ty>>),
+    /// `Vec<_>`, `HashSet<_>`, ... — see [`CollectionKind`].
+    Collection(CollectionKind, Box<Sp<Ty>>),
 }
 
 impl Ty {
     pub fn from_syn_ty(ty: &syn::Type) -> Sp<Self> {
-        use self::Ty::*;
-        let t = |kind| Sp::new(kind, ty.span());
+        Self::parse(ty, false)
+    }
 
-        if is_unit_ty(ty) {
-            t(Unit)
-        } else if let Some(vt) = get_vec_ty(ty, Vec, VecVec) {
-            t(vt)
+    fn parse(ty: &syn::Type, nested_in_collection: bool) -> Sp<Self> {
+        let span = ty.span();
+
+        let kind = if let Some(name) = smart_pointer_name(ty) {
+            let subty = subty_if_name(ty, name).expect("just matched by smart_pointer_name");
+            Self::Pointer(name, Box::new(Self::parse(subty, nested_in_collection)))
+        } else if is_unit_ty(ty) {
+            Self::Unit
+        } else if let Some((kind, subty)) = collection_of(ty, nested_in_collection) {
+            Self::Collection(kind, Box::new(Self::parse(subty, true)))
         } else if let Some(subty) = subty_if_name(ty, "Option") {
-            if is_generic_ty(subty, "Option") {
-                t(OptionOption)
-            } else if let Some(vt) = get_vec_ty(subty, OptionVec, OptionVecVec) {
-                t(vt)
-            } else {
-                t(Option)
+            Self::Optional(Box::new(Self::parse(subty, nested_in_collection)))
+        } else {
+            Self::Leaf
+        };
+
+        Sp::new(kind, span)
+    }
+
+    /// Renders the nesting for diagnostics, e.g. `Option<Vec<T>>`.
+    pub fn as_str(&self) -> String {
+        fn render(ty: &Ty) -> String {
+            match ty {
+                Ty::Unit => "()".to_owned(),
+                Ty::Leaf => "T".to_owned(),
+                Ty::Pointer(name, inner) => format!("{}<{}>", name, render(inner)),
+                Ty::Optional(inner) => format!("Option<{}>", render(inner)),
+                Ty::Collection(kind, inner) => format!("{}<{}>", kind.name(), render(inner)),
             }
+        }
+        if self.is_other() {
+            "...other...".to_owned()
         } else {
-            t(Other)
+            render(self)
         }
     }
 
-    pub fn as_str(&self) -> &'static str {
+    fn skip_pointers(&self) -> &Self {
         match self {
-            Self::Unit => "()",
-            Self::Vec => "Vec<T>",
-            Self::Option => "Option<T>",
-            Self::OptionOption => "Option<Option<T>>",
-            Self::OptionVec => "Option<Vec<T>>",
-            Self::VecVec => "Vec<Vec<T>>",
-            Self::OptionVecVec => "Option<Vec<Vec<T>>>",
-            Self::Other => "...other...",
+            Self::Pointer(_, inner) => inner.skip_pointers(),
+            other => other,
         }
     }
 
     #[inline]
     pub fn is_other(&self) -> bool {
-        matches!(self, Self::Other)
+        matches!(self.skip_pointers(), Self::Leaf)
+    }
+
+    #[inline]
+    pub fn is_optional(&self) -> bool {
+        matches!(self.skip_pointers(), Self::Optional(_))
+    }
+
+    /// How many collection layers wrap the innermost value, through `Option` too.
+    pub fn collection_depth(&self) -> usize {
+        match self.skip_pointers() {
+            Self::Collection(_, inner) => 1 + inner.collection_depth(),
+            Self::Optional(inner) => inner.collection_depth(),
+            Self::Unit | Self::Leaf => 0,
+            Self::Pointer(..) => unreachable!("skip_pointers peels all Pointer layers"),
+        }
+    }
+
+    /// Descends to the innermost `Unit`/`Leaf`.
+    pub fn innermost(&self) -> &Self {
+        match self.skip_pointers() {
+            Self::Optional(inner) | Self::Collection(_, inner) => inner.innermost(),
+            other => other,
+        }
     }
 }
 
 pub fn inner_type(field_ty: &syn::Type) -> &syn::Type {
-    let ty = Ty::from_syn_ty(field_ty);
-    match *ty {
-        Ty::Vec | Ty::Option => sub_type(field_ty).unwrap_or(field_ty),
-        Ty::OptionOption | Ty::OptionVec | Ty::VecVec => {
-            sub_type(field_ty).and_then(sub_type).unwrap_or(field_ty)
-        }
-        Ty::OptionVecVec => sub_type(field_ty)
-            .and_then(sub_type)
-            .and_then(sub_type)
-            .unwrap_or(field_ty),
-        _ => field_ty,
+    descend(field_ty, false)
+}
+
+fn descend(ty: &syn::Type, nested_in_collection: bool) -> &syn::Type {
+    if let Some(name) = smart_pointer_name(ty) {
+        let subty = subty_if_name(ty, name).expect("just matched by smart_pointer_name");
+        return descend(subty, nested_in_collection);
+    }
+
+    if let Some((_, subty)) = collection_of(ty, nested_in_collection) {
+        descend(subty, true)
+    } else if let Some(subty) = subty_if_name(ty, "Option") {
+        descend(subty, nested_in_collection)
+    } else {
+        ty
     }
 }
 
@@ -79,6 +151,30 @@ pub fn sub_type(ty: &syn::Type) -> Option<&syn::Type> {
     subty_if(ty, |_| true)
 }
 
+/// Finds the element type of `ty` if it's one of [`CollectionKind::ALL`]. A collection
+/// nested inside another one is gated behind `unstable-v5`, same as `Vec<Vec<T>>` was.
+fn collection_of(
+    ty: &syn::Type,
+    nested_in_collection: bool,
+) -> Option<(CollectionKind, &syn::Type)> {
+    if nested_in_collection && !cfg!(feature = "unstable-v5") {
+        return None;
+    }
+    CollectionKind::ALL
+        .iter()
+        .find_map(|&(name, kind)| subty_if_name(ty, name).map(|subty| (kind, subty)))
+}
+
+const SMART_POINTERS: &[&str] = &["Box", "Rc", "Arc"];
+
+/// Returns the name of the outermost `Box`/`Rc`/`Arc` wrapping `ty`, if any.
+pub fn smart_pointer_name(ty: &syn::Type) -> Option<&'static str> {
+    SMART_POINTERS
+        .iter()
+        .find(|name| subty_if_name(ty, name).is_some())
+        .copied()
+}
+
 fn only_last_segment(mut ty: &syn::Type) -> Option<&PathSegment> {
     while let syn::Type::Group(syn::TypeGroup { elem, .. }) = ty {
         ty = elem;
@@ -86,12 +182,8 @@ fn only_last_segment(mut ty: &syn::Type) -> Option<&PathSegment> {
     match ty {
         Type::Path(TypePath {
             qself: None,
-            path:
-                Path {
-                    leading_colon: None,
-                    segments,
-                },
-        }) => only_one(segments.iter()),
+            path: Path { segments, .. },
+        }) => segments.last(),
 
         _ => None,
     }
@@ -134,10 +226,6 @@ pub fn is_simple_ty(ty: &syn::Type, name: &str) -> bool {
         .unwrap_or(false)
 }
 
-fn is_generic_ty(ty: &syn::Type, name: &str) -> bool {
-    subty_if_name(ty, name).is_some()
-}
-
 fn is_unit_ty(ty: &syn::Type) -> bool {
     if let syn::Type::Tuple(tuple) = ty {
         tuple.elems.is_empty()
@@ -153,18 +241,112 @@ where
     iter.next().filter(|_| iter.next().is_none())
 }
 
-#[cfg(feature = "unstable-v5")]
-fn get_vec_ty(ty: &Type, vec_ty: Ty, vecvec_ty: Ty) -> Option<Ty> {
-    subty_if_name(ty, "Vec").map(|subty| {
-        if is_generic_ty(subty, "Vec") {
-            vecvec_ty
-        } else {
-            vec_ty
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ty(src: &str) -> syn::Type {
+        syn::parse_str(src).unwrap()
+    }
+
+    #[test]
+    fn recognizes_leading_colon_path() {
+        assert_eq!(
+            Ty::from_syn_ty(&ty("::std::vec::Vec<u8>")).collection_depth(),
+            1
+        );
+    }
+
+    #[test]
+    fn recognizes_multi_segment_qualified_option() {
+        assert!(Ty::from_syn_ty(&ty("std::option::Option<String>")).is_optional());
+    }
+
+    #[test]
+    fn qualified_and_bare_paths_agree_on_inner_type() {
+        assert!(is_simple_ty(inner_type(&ty("Option<String>")), "String"));
+        assert!(is_simple_ty(
+            inner_type(&ty("::core::option::Option<String>")),
+            "String"
+        ));
+    }
+
+    #[test]
+    fn box_of_vec_behaves_like_vec() {
+        assert_eq!(
+            Ty::from_syn_ty(&ty("Box<Vec<String>>")).collection_depth(),
+            1
+        );
+        assert!(is_simple_ty(inner_type(&ty("Box<Vec<String>>")), "String"));
+    }
+
+    #[test]
+    fn option_of_box_behaves_like_option() {
+        assert!(Ty::from_syn_ty(&ty("Option<Box<String>>")).is_optional());
+    }
+
+    #[test]
+    fn pointer_depth_is_recorded_not_collapsed() {
+        assert_eq!(
+            Ty::from_syn_ty(&ty("Vec<Box<String>>")).as_str(),
+            "Vec<Box<T>>"
+        );
+        assert_eq!(
+            Ty::from_syn_ty(&ty("Option<Box<Vec<String>>>")).as_str(),
+            "Option<Box<Vec<T>>>"
+        );
+    }
+
+    #[test]
+    fn unrecognized_pointee_renders_as_other_like_is_other() {
+        let parsed = Ty::from_syn_ty(&ty("Box<String>"));
+        assert!(parsed.is_other());
+        assert_eq!(parsed.as_str(), "...other...");
+    }
+
+    #[test]
+    fn recognizes_set_and_deque_collections() {
+        for src in [
+            "HashSet<String>",
+            "BTreeSet<String>",
+            "VecDeque<String>",
+            "BinaryHeap<String>",
+        ] {
+            assert_eq!(Ty::from_syn_ty(&ty(src)).collection_depth(), 1, "{src}");
+            assert!(is_simple_ty(inner_type(&ty(src)), "String"), "{src}");
         }
-    })
-}
+    }
+
+    #[test]
+    fn optional_set_is_recognized() {
+        let parsed = Ty::from_syn_ty(&ty("Option<HashSet<String>>"));
+        assert!(parsed.is_optional());
+        assert_eq!(parsed.collection_depth(), 1);
+    }
+
+    #[test]
+    fn vec_of_option_is_representable() {
+        let parsed = Ty::from_syn_ty(&ty("Vec<Option<String>>"));
+        assert_eq!(parsed.as_str(), "Vec<Option<T>>");
+        assert_eq!(parsed.collection_depth(), 1);
+        assert!(matches!(parsed.innermost(), Ty::Leaf));
+    }
+
+    #[cfg(feature = "unstable-v5")]
+    #[test]
+    fn nested_collection_depth_under_unstable_v5() {
+        assert_eq!(
+            Ty::from_syn_ty(&ty("Vec<Option<Vec<String>>>")).collection_depth(),
+            2
+        );
+    }
 
-#[cfg(not(feature = "unstable-v5"))]
-fn get_vec_ty(ty: &Type, vec_ty: Ty, _vecvec_ty: Ty) -> Option<Ty> {
-    is_generic_ty(ty, "Vec").then_some(vec_ty)
+    #[cfg(not(feature = "unstable-v5"))]
+    #[test]
+    fn nested_collection_capped_without_unstable_v5() {
+        assert_eq!(
+            Ty::from_syn_ty(&ty("Vec<Option<Vec<String>>>")).collection_depth(),
+            1
+        );
+    }
 }